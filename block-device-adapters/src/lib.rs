@@ -0,0 +1,14 @@
+//! Block device adapters
+
+#![cfg_attr(not(test), no_std)]
+
+// MUST be the first module listed
+mod fmt;
+
+mod buf_stream;
+mod nor_flash;
+mod stream_slice;
+
+pub use buf_stream::{BufStream, BufStreamError};
+pub use nor_flash::NorFlashBlockDevice;
+pub use stream_slice::{StreamSlice, StreamSliceError};
@@ -0,0 +1,285 @@
+use aligned::{Aligned, A4};
+use block_device_driver::{BlockDevice, Discard};
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+
+/// A [`BlockDevice`] adapter exposing raw NOR/QSPI flash as a 512-byte logical block device.
+///
+/// [`NorFlashBlockDevice<F, const ERASE: usize, const BLOCK: usize>`](NorFlashBlockDevice) can be
+/// initialized with the following parameters.
+///
+/// - `F`: The inner [`NorFlash`] chip.
+/// - `ERASE`: The size in bytes of a single erase sector on the chip.
+/// - `BLOCK`: The logical block size exposed to callers, typically `512`.
+///
+/// NOR flash can only clear bits on an erase-sector boundary, while the logical block size
+/// wanted by a filesystem is usually much smaller. This adapter keeps a single erase-sector
+/// buffer in RAM and performs the read-modify-write cycle required to turn sector-granularity
+/// erases into `BLOCK`-sized random access: a logical write ensures the target sector is
+/// loaded (reading it in and flushing any other dirty sector first), patches the `BLOCK`-sized
+/// region in the buffer, and marks it dirty. The dirty buffer is only committed to flash, via
+/// an erase followed by a write of the whole sector, when [`flush`](Self::flush) is called.
+///
+/// `ERASE` must be a multiple of `BLOCK`, and the flash's capacity must be an integer number of
+/// erase sectors; both are checked on construction.
+///
+/// Because committing a dirty buffer requires an erase and a write, callers **must** call
+/// [`flush`](Self::flush) before dropping a [`NorFlashBlockDevice`] with outstanding writes --
+/// async drop glue isn't available, so [`Drop`] can only warn about data loss, not prevent it.
+pub struct NorFlashBlockDevice<F, const ERASE: usize, const BLOCK: usize> {
+    flash: F,
+    buffer: Aligned<A4, [u8; ERASE]>,
+    current_sector: u32,
+    dirty: bool,
+}
+
+impl<F: ReadNorFlash, const ERASE: usize, const BLOCK: usize> NorFlashBlockDevice<F, ERASE, BLOCK> {
+    /// Create a new [`NorFlashBlockDevice`] around a raw NOR/QSPI flash chip.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ERASE % BLOCK != 0` or if the flash's capacity is not an integer number of
+    /// erase sectors.
+    pub fn new(flash: F) -> Self {
+        assert!(ERASE % BLOCK == 0, "ERASE must be a multiple of BLOCK");
+        assert!(
+            flash.capacity() % ERASE == 0,
+            "flash capacity must be an integer number of erase sectors"
+        );
+        Self {
+            flash,
+            buffer: Aligned([0; ERASE]),
+            current_sector: u32::MAX,
+            dirty: false,
+        }
+    }
+
+    #[inline]
+    fn sector_of(block_address: u32) -> u32 {
+        (block_address as u64 * BLOCK as u64 / ERASE as u64) as u32
+    }
+
+    #[inline]
+    fn offset_in_sector(block_address: u32, sector: u32) -> usize {
+        (block_address as u64 * BLOCK as u64 - sector as u64 * ERASE as u64) as usize
+    }
+}
+
+impl<F: NorFlash, const ERASE: usize, const BLOCK: usize> NorFlashBlockDevice<F, ERASE, BLOCK> {
+    /// Commit the buffered erase sector to flash, if it has been modified.
+    ///
+    /// This erases the sector and rewrites it in full, so it must be called before dropping
+    /// the device if any writes are outstanding.
+    pub async fn flush(&mut self) -> Result<(), F::Error> {
+        if self.dirty {
+            let base = self.current_sector * ERASE as u32;
+            self.flash.erase(base, base + ERASE as u32).await?;
+            self.flash.write(base, &self.buffer[..]).await?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    async fn ensure_loaded(&mut self, sector: u32) -> Result<(), F::Error> {
+        if self.current_sector != sector {
+            self.flush().await?;
+            self.flash
+                .read(sector * ERASE as u32, &mut self.buffer[..])
+                .await?;
+            self.current_sector = sector;
+        }
+        Ok(())
+    }
+}
+
+impl<F, const ERASE: usize, const BLOCK: usize> Drop for NorFlashBlockDevice<F, ERASE, BLOCK> {
+    fn drop(&mut self) {
+        if self.dirty {
+            warn!("NorFlashBlockDevice dropped with a dirty buffer, data was lost; call flush() first");
+        }
+    }
+}
+
+impl<F: NorFlash, const ERASE: usize, const BLOCK: usize> BlockDevice<BLOCK>
+    for NorFlashBlockDevice<F, ERASE, BLOCK>
+{
+    type Error = F::Error;
+    type Align = A4;
+
+    async fn read(
+        &mut self,
+        block_address: u32,
+        data: &mut [Aligned<Self::Align, [u8; BLOCK]>],
+    ) -> Result<(), Self::Error> {
+        for (i, block) in data.iter_mut().enumerate() {
+            let block_address = block_address + i as u32;
+            let sector = Self::sector_of(block_address);
+            if sector == self.current_sector {
+                let offset = Self::offset_in_sector(block_address, sector);
+                block[..].copy_from_slice(&self.buffer[offset..offset + BLOCK]);
+            } else {
+                self.flash
+                    .read(block_address * BLOCK as u32, &mut block[..])
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(
+        &mut self,
+        block_address: u32,
+        data: &[Aligned<Self::Align, [u8; BLOCK]>],
+    ) -> Result<(), Self::Error> {
+        for (i, block) in data.iter().enumerate() {
+            let block_address = block_address + i as u32;
+            let sector = Self::sector_of(block_address);
+            self.ensure_loaded(sector).await?;
+            let offset = Self::offset_in_sector(block_address, sector);
+            self.buffer[offset..offset + BLOCK].copy_from_slice(&block[..]);
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn size(&mut self) -> Result<u64, Self::Error> {
+        Ok(self.flash.capacity() as u64)
+    }
+}
+
+impl<F: NorFlash, const ERASE: usize, const BLOCK: usize> Discard<BLOCK>
+    for NorFlashBlockDevice<F, ERASE, BLOCK>
+{
+    /// Erase every sector covered by `block_address..block_address + count`.
+    ///
+    /// This erases the whole sector directly on flash rather than going through the buffered
+    /// read-modify-write path. If the currently buffered sector falls inside the discarded
+    /// range, its pending writes are dropped instead of flushed, since the blocks they'd write
+    /// are being discarded anyway.
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        if count == 0 {
+            return Ok(());
+        }
+        let first_sector = Self::sector_of(block_address);
+        let last_sector = Self::sector_of(block_address + count - 1);
+
+        if self.current_sector >= first_sector && self.current_sector <= last_sector {
+            self.dirty = false;
+            self.current_sector = u32::MAX;
+        }
+
+        for sector in first_sector..=last_sector {
+            let base = sector * ERASE as u32;
+            self.flash.erase(base, base + ERASE as u32).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use embedded_storage_async::nor_flash::ErrorType;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct MockFlash {
+        data: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl MockFlash {
+        fn new(size: usize) -> Self {
+            Self {
+                data: Rc::new(RefCell::new(vec![0xFF; size])),
+            }
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = core::convert::Infallible;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data.borrow()[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.borrow().len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = 4096;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data.borrow_mut()[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            self.data.borrow_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn write_then_read_back_before_flush() {
+        let mut dev: NorFlashBlockDevice<_, 4096, 512> =
+            NorFlashBlockDevice::new(MockFlash::new(3 * 4096));
+
+        let block: Aligned<A4, [u8; 512]> = Aligned([0xAB; 512]);
+        dev.write(2, core::slice::from_ref(&block)).await.unwrap();
+
+        let mut out = [Aligned::<A4, [u8; 512]>([0; 512])];
+        dev.read(2, &mut out).await.unwrap();
+        assert_eq!(&out[0][..], &block[..]);
+
+        dev.flush().await.unwrap();
+        assert!(!dev.dirty);
+    }
+
+    #[tokio::test]
+    async fn flush_erases_and_rewrites_whole_sector() {
+        let flash = MockFlash::new(4096);
+        let mut dev: NorFlashBlockDevice<_, 4096, 512> = NorFlashBlockDevice::new(flash.clone());
+
+        let block: Aligned<A4, [u8; 512]> = Aligned([0x42; 512]);
+        dev.write(1, core::slice::from_ref(&block)).await.unwrap();
+        dev.flush().await.unwrap();
+
+        let data = flash.data.borrow();
+        assert_eq!(&data[512..1024], &[0x42; 512]);
+        assert_eq!(&data[..512], &[0xFF; 512]);
+    }
+
+    #[test]
+    #[should_panic(expected = "multiple of BLOCK")]
+    fn rejects_erase_not_multiple_of_block() {
+        let _dev: NorFlashBlockDevice<_, 100, 512> = NorFlashBlockDevice::new(MockFlash::new(100));
+    }
+
+    #[tokio::test]
+    async fn discard_erases_covered_sectors_and_drops_buffered_writes() {
+        let flash = MockFlash::new(2 * 4096);
+        let mut dev: NorFlashBlockDevice<_, 4096, 512> = NorFlashBlockDevice::new(flash.clone());
+
+        let block: Aligned<A4, [u8; 512]> = Aligned([0x42; 512]);
+        dev.write(1, core::slice::from_ref(&block)).await.unwrap();
+        flash.data.borrow_mut()[4096..4096 + 512].fill(0x99);
+
+        dev.discard(1, 1).await.unwrap();
+        assert!(!dev.dirty);
+
+        let data = flash.data.borrow();
+        assert_eq!(&data[..4096], &[0xFF; 4096][..]);
+        assert_eq!(&data[4096..4096 + 512], &[0x99; 512]);
+    }
+}
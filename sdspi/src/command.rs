@@ -0,0 +1,71 @@
+//! SD SPI-mode command frames.
+//!
+//! Every command is a fixed 6-byte frame: a start/transmission bit, the 6-bit command
+//! index, a 32-bit big-endian argument, and a 7-bit CRC followed by the stop bit. Cards are
+//! allowed to ignore the CRC once CRC checking is disabled (the default out of reset), with
+//! the exception of CMD0 and CMD8 whose CRC must always be valid because the card can't yet
+//! know whether CRC checking is enabled.
+
+pub(crate) const CMD0_GO_IDLE_STATE: u8 = 0;
+pub(crate) const CMD8_SEND_IF_COND: u8 = 8;
+pub(crate) const CMD9_SEND_CSD: u8 = 9;
+pub(crate) const CMD12_STOP_TRANSMISSION: u8 = 12;
+pub(crate) const CMD16_SET_BLOCKLEN: u8 = 16;
+pub(crate) const CMD17_READ_SINGLE_BLOCK: u8 = 17;
+pub(crate) const CMD18_READ_MULTIPLE_BLOCK: u8 = 18;
+pub(crate) const CMD24_WRITE_BLOCK: u8 = 24;
+pub(crate) const CMD25_WRITE_MULTIPLE_BLOCK: u8 = 25;
+pub(crate) const CMD32_ERASE_WR_BLK_START: u8 = 32;
+pub(crate) const CMD33_ERASE_WR_BLK_END: u8 = 33;
+pub(crate) const CMD38_ERASE: u8 = 38;
+pub(crate) const CMD55_APP_CMD: u8 = 55;
+pub(crate) const CMD58_READ_OCR: u8 = 58;
+#[cfg(feature = "crc")]
+pub(crate) const CMD59_CRC_ON_OFF: u8 = 59;
+pub(crate) const ACMD41_SD_SEND_OP_COND: u8 = 41;
+
+/// Token that precedes a single data block on a CMD17/CMD18/CMD24 data phase.
+pub(crate) const DATA_START_BLOCK: u8 = 0xFE;
+/// Token that precedes each data block of a CMD25 write.
+pub(crate) const WRITE_MULTIPLE_TOKEN: u8 = 0xFC;
+/// Token that ends a CMD25 write.
+pub(crate) const STOP_TRAN_TOKEN: u8 = 0xFD;
+
+/// Precomputed CRC7 (as `(crc << 1) | 1`) for CMD0 with argument 0.
+#[cfg(not(feature = "crc"))]
+const CMD0_CRC: u8 = 0x95;
+/// Precomputed CRC7 for CMD8 with argument `0x1AA` (the voltage/check-pattern used during init).
+#[cfg(not(feature = "crc"))]
+const CMD8_CRC: u8 = 0x87;
+/// CRC is not checked for any other command while CRC mode is disabled; the stop bit still has
+/// to be set, so `0x01` is sent in its place.
+#[cfg(not(feature = "crc"))]
+const DUMMY_CRC: u8 = 0x01;
+
+/// Build the 6-byte command frame for `cmd` with the given argument.
+///
+/// With the `crc` feature disabled the card isn't checking command CRCs (the default out of
+/// reset), so only CMD0 and CMD8 -- whose CRC must always be valid -- get a real one; every
+/// other command gets a dummy CRC byte with just the stop bit set, which is cheaper than
+/// computing a CRC7 that the card will ignore. With `crc` enabled a real CRC7 is computed for
+/// every command, matching the card-side checking enabled via CMD59.
+pub(crate) fn frame(cmd: u8, arg: u32) -> [u8; 6] {
+    let mut frame = [0u8; 6];
+    frame[0] = 0x40 | (cmd & 0x3F);
+    frame[1..5].copy_from_slice(&arg.to_be_bytes());
+
+    #[cfg(feature = "crc")]
+    {
+        frame[5] = crate::crc::crc7(&frame[..5]);
+    }
+    #[cfg(not(feature = "crc"))]
+    {
+        frame[5] = match cmd {
+            CMD0_GO_IDLE_STATE => CMD0_CRC,
+            CMD8_SEND_IF_COND => CMD8_CRC,
+            _ => DUMMY_CRC,
+        };
+    }
+
+    frame
+}
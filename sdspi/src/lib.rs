@@ -0,0 +1,988 @@
+//! An SD/MMC card driver speaking the SPI-mode protocol.
+//!
+//! # Sleep/wake and cut power
+//!
+//! [`SdSpi::sleep`] and [`SdSpi::wake`] pair with [`SdSpi::with_power_pin`] to cut a card's
+//! supply rail between uses. [`SdSpi::wake`] restores power and re-runs [`SdSpi::init`], but it
+//! cannot also re-clock the 74+ dummy cycles the SPI-mode spec requires after a power-up: doing
+//! that requires driving the bus with CS deasserted, which isn't possible through the
+//! CS-managing [`embedded_hal_async::spi::SpiDevice`] this type wraps. If [`SdSpi::with_power_pin`]
+//! is cutting the card's actual supply (as opposed to gating a rail that's already kept powered),
+//! call [`sd_init`] on the raw bus yourself, with CS held high, after restoring power and before
+//! calling [`SdSpi::wake`] -- otherwise the card may fail to leave idle state and `wake` returns a
+//! generic [`Error::Timeout`] or [`Error::CardNotFound`] with no hint that the dummy clocks were
+//! the missing step.
+
+#![cfg_attr(not(test), no_std)]
+#![allow(async_fn_in_trait)]
+
+// MUST be the first module listed
+mod fmt;
+
+mod command;
+#[cfg(feature = "crc")]
+mod crc;
+
+use aligned::Aligned;
+use block_device_driver::{BlockDevice, Discard};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::{delay::DelayNs, spi::SpiDevice};
+
+const BLOCK_SIZE: usize = 512;
+
+/// A no-op stand-in for [`SdSpi`]'s power-enable pin when the card's supply isn't software
+/// controlled, so [`SdSpi::sleep`] only deselects the card instead of cutting its power.
+pub struct NoPowerPin;
+
+impl embedded_hal::digital::ErrorType for NoPowerPin {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for NoPowerPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Error type for [`SdSpi`] operations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error<E> {
+    /// An error occurred in the underlying SPI bus.
+    Spi(E),
+    /// The card did not respond in time.
+    Timeout,
+    /// The card did not enter idle state during initialization.
+    CardNotFound,
+    /// The card responded to a command with an unexpected R1 byte.
+    BadResponse(u8),
+    /// The card rejected a written data block (the data-response token was not "accepted").
+    WriteRejected(u8),
+    /// A data block's CRC16 did not match its trailing checksum bytes (only produced with the
+    /// `crc` feature enabled).
+    CrcMismatch,
+    /// The card is asleep; call [`SdSpi::wake`] before transferring data.
+    Asleep,
+    /// Toggling the power-enable pin failed.
+    PowerPin,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Spi(e)
+    }
+}
+
+/// The kind of card detected during [`SdSpi::init`], which determines how block addresses are
+/// encoded in command arguments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CardType {
+    /// Version 1 or non-SDHC version 2 card; addresses are byte offsets.
+    ByteAddressed,
+    /// SDHC/SDXC card; addresses are block numbers.
+    BlockAddressed,
+}
+
+/// Clock out 74+ dummy clock cycles with CS deasserted, as required by the SD SPI-mode spec
+/// before the first command is sent.
+///
+/// This must be called on the raw SPI bus before it is wrapped in a CS-managing [`SpiDevice`].
+pub async fn sd_init<SPI: embedded_hal_async::spi::SpiBus<u8>>(
+    spi: &mut SPI,
+) -> Result<(), SPI::Error> {
+    let dummy = [0xFFu8; 10];
+    spi.write(&dummy).await
+}
+
+/// An SD/MMC card accessed over SPI, exposed as a 512-byte [`BlockDevice`].
+///
+/// [`SdSpi<SPI, DELAY, ALIGN, POWER>`](SdSpi) can be initialized with the following parameters.
+///
+/// - `SPI`: The [`SpiDevice`] used to talk to the card.
+/// - `DELAY`: A [`DelayNs`] implementation used while polling the card.
+/// - `ALIGN`: The [`aligned::Alignment`] of the block buffers passed to [`BlockDevice`].
+/// - `POWER`: An [`OutputPin`] gating the card's supply rail, used by [`sleep`](Self::sleep) and
+///   [`wake`](Self::wake). Defaults to [`NoPowerPin`] for cards whose supply isn't software
+///   controlled.
+pub struct SdSpi<SPI, DELAY, ALIGN, POWER = NoPowerPin> {
+    spi: SPI,
+    delay: DELAY,
+    card_type: Option<CardType>,
+    power: POWER,
+    asleep: bool,
+    sleep_settle_us: u32,
+    wake_settle_us: u32,
+    _align: core::marker::PhantomData<ALIGN>,
+}
+
+impl<SPI: SpiDevice, DELAY: DelayNs, ALIGN> SdSpi<SPI, DELAY, ALIGN> {
+    /// Create a new [`SdSpi`] around an already-selected [`SpiDevice`], with no power-enable pin.
+    ///
+    /// Call [`sd_init`] on the raw bus before constructing the [`SpiDevice`], then [`init`](Self::init)
+    /// before any other method.
+    pub fn new(spi: SPI, delay: DELAY) -> Self {
+        Self {
+            spi,
+            delay,
+            card_type: None,
+            power: NoPowerPin,
+            asleep: false,
+            sleep_settle_us: 0,
+            wake_settle_us: 0,
+            _align: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<SPI: SpiDevice, DELAY: DelayNs, ALIGN, POWER: OutputPin> SdSpi<SPI, DELAY, ALIGN, POWER> {
+    /// Create a new [`SdSpi`] with a power-enable pin that [`sleep`](Self::sleep) and
+    /// [`wake`](Self::wake) drive to cut and restore the card's supply.
+    pub fn with_power_pin(spi: SPI, delay: DELAY, power: POWER) -> Self {
+        Self {
+            spi,
+            delay,
+            card_type: None,
+            power,
+            asleep: false,
+            sleep_settle_us: 0,
+            wake_settle_us: 0,
+            _align: core::marker::PhantomData,
+        }
+    }
+
+    /// Configure how long [`sleep`](Self::sleep) waits after driving the power pin low, giving
+    /// the supply rail time to discharge. Defaults to `0`.
+    pub fn set_sleep_settle_us(&mut self, us: u32) {
+        self.sleep_settle_us = us;
+    }
+
+    /// Configure how long [`wake`](Self::wake) waits after driving the power pin high before
+    /// re-running the init sequence, giving the card time to power up. Defaults to `0`.
+    pub fn set_wake_settle_us(&mut self, us: u32) {
+        self.wake_settle_us = us;
+    }
+
+    /// Put the card to sleep: deselect it and, if a power-enable pin was attached via
+    /// [`with_power_pin`](Self::with_power_pin), drive it low to cut the card's supply.
+    ///
+    /// Transfer methods return [`Error::Asleep`] while asleep rather than transparently waking
+    /// the card, since re-running the whole init sequence isn't free and callers should opt into
+    /// paying for it by calling [`wake`](Self::wake) themselves.
+    pub async fn sleep(&mut self) -> Result<(), Error<SPI::Error>> {
+        if self.asleep {
+            return Ok(());
+        }
+        self.power.set_low().map_err(|_| Error::PowerPin)?;
+        self.delay.delay_us(self.sleep_settle_us).await;
+        self.card_type = None;
+        self.asleep = true;
+        Ok(())
+    }
+
+    /// Wake the card back up: restore power via the power-enable pin (if any) and re-run
+    /// [`init`](Self::init).
+    ///
+    /// This does *not* re-clock the 74+ dummy cycles the SPI-mode spec requires after a power
+    /// cycle: doing that correctly means driving the bus with CS deasserted, which isn't
+    /// possible through the CS-managing [`SpiDevice`] this type wraps -- the same reason
+    /// [`sd_init`] has to run on the raw bus before construction. If [`with_power_pin`](Self::with_power_pin)
+    /// is cutting the card's actual supply, call [`sd_init`] on the raw bus yourself, with CS
+    /// held high, after restoring power and before calling `wake`.
+    pub async fn wake(&mut self) -> Result<(), Error<SPI::Error>> {
+        if !self.asleep {
+            return Ok(());
+        }
+        self.power.set_high().map_err(|_| Error::PowerPin)?;
+        self.delay.delay_us(self.wake_settle_us).await;
+        self.asleep = false;
+        self.init().await
+    }
+}
+
+impl<SPI: SpiDevice, DELAY: DelayNs, ALIGN, POWER> SdSpi<SPI, DELAY, ALIGN, POWER> {
+    fn check_awake(&self) -> Result<(), Error<SPI::Error>> {
+        if self.asleep {
+            Err(Error::Asleep)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns a mutable reference to the inner SPI device, e.g. to raise the bus clock speed
+    /// after initialization.
+    pub fn spi(&mut self) -> &mut SPI {
+        &mut self.spi
+    }
+
+    /// Returns inner object.
+    pub fn into_inner(self) -> SPI {
+        self.spi
+    }
+
+    /// Initialize the card: reset it, negotiate voltage range, wait for it to leave idle state,
+    /// and determine whether it uses byte or block addressing.
+    pub async fn init(&mut self) -> Result<(), Error<SPI::Error>> {
+        let mut r1 = 0xFF;
+        for _ in 0..10 {
+            r1 = self.command(command::CMD0_GO_IDLE_STATE, 0).await?;
+            if r1 == 0x01 {
+                break;
+            }
+            self.delay.delay_ms(1).await;
+        }
+        if r1 != 0x01 {
+            return Err(Error::CardNotFound);
+        }
+
+        // With the `crc` feature enabled, ask the card to start checking command and data CRCs;
+        // we've been computing real ones all along (see `command::frame`), this just makes the
+        // card enforce them too.
+        #[cfg(feature = "crc")]
+        {
+            let r1 = self.command(command::CMD59_CRC_ON_OFF, 1).await?;
+            if r1 & !0x01 != 0 {
+                return Err(Error::BadResponse(r1));
+            }
+        }
+
+        // CMD8 tells us whether this is a version 2 card, and checks the voltage range.
+        let r1 = self.command(command::CMD8_SEND_IF_COND, 0x1AA).await?;
+        let mut is_v2 = false;
+        if r1 & 0x04 == 0 {
+            let r7 = self.read_bytes::<4>().await?;
+            if r7[2] != 0x01 || r7[3] != 0xAA {
+                return Err(Error::CardNotFound);
+            }
+            is_v2 = true;
+        }
+
+        // ACMD41 brings the card out of idle state; set HCS when we support high capacity.
+        let hcs = if is_v2 { 0x4000_0000 } else { 0 };
+        let mut ready = false;
+        for _ in 0..4096 {
+            let r1 = self.acmd(command::ACMD41_SD_SEND_OP_COND, hcs).await?;
+            if r1 == 0x00 {
+                ready = true;
+                break;
+            }
+            if r1 & !0x01 != 0 {
+                return Err(Error::BadResponse(r1));
+            }
+            self.delay.delay_ms(1).await;
+        }
+        if !ready {
+            return Err(Error::Timeout);
+        }
+
+        self.card_type = Some(if is_v2 {
+            // CMD58 reads the OCR; bit 30 (CCS) tells us if the card is block addressed.
+            let r1 = self.command(command::CMD58_READ_OCR, 0).await?;
+            if r1 != 0x00 {
+                return Err(Error::BadResponse(r1));
+            }
+            let ocr = self.read_bytes::<4>().await?;
+            if ocr[0] & 0x40 != 0 {
+                CardType::BlockAddressed
+            } else {
+                CardType::ByteAddressed
+            }
+        } else {
+            CardType::ByteAddressed
+        });
+
+        if self.card_type == Some(CardType::ByteAddressed) {
+            // Byte-addressed cards default to a 512 byte block length, but don't all guarantee
+            // it, so set it explicitly.
+            let r1 = self.command(command::CMD16_SET_BLOCKLEN, BLOCK_SIZE as u32).await?;
+            if r1 != 0x00 {
+                return Err(Error::BadResponse(r1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read `start_block..start_block + blocks.len()` into `blocks`.
+    ///
+    /// Issues CMD18 (READ_MULTIPLE_BLOCK) for runs of more than one block, falling back to
+    /// CMD17 (READ_SINGLE_BLOCK) for a single block.
+    pub async fn read_blocks(
+        &mut self,
+        start_block: u32,
+        blocks: &mut [[u8; BLOCK_SIZE]],
+    ) -> Result<(), Error<SPI::Error>> {
+        // Safety: `[u8; BLOCK_SIZE]` has no padding, so `blocks.len()` contiguous elements are
+        // exactly equivalent to a flat byte slice of `blocks.len() * BLOCK_SIZE` bytes.
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(blocks.as_mut_ptr().cast::<u8>(), core::mem::size_of_val(blocks))
+        };
+        self.read_blocks_raw(start_block, buf).await
+    }
+
+    /// Write `blocks` starting at `start_block`.
+    ///
+    /// Issues CMD25 (WRITE_MULTIPLE_BLOCK) for runs of more than one block, falling back to
+    /// CMD24 (WRITE_BLOCK) for a single block.
+    pub async fn write_blocks(
+        &mut self,
+        start_block: u32,
+        blocks: &[[u8; BLOCK_SIZE]],
+    ) -> Result<(), Error<SPI::Error>> {
+        // Safety: see `read_blocks`.
+        let buf = unsafe {
+            core::slice::from_raw_parts(blocks.as_ptr().cast::<u8>(), core::mem::size_of_val(blocks))
+        };
+        self.write_blocks_raw(start_block, buf).await
+    }
+
+    /// Erase `start_block..end_block` (end exclusive), telling the card the range no longer
+    /// holds live data.
+    ///
+    /// This is a hint, not a guarantee the blocks read back as zero: CMD32/33/38 ask the card to
+    /// erase the range using whatever erase granularity it prefers, which is usually much coarser
+    /// than a single block. Cards are allowed to erase to either all-zero or all-one bits, or to
+    /// leave stale data in place as long as it's no longer addressable.
+    pub async fn erase_blocks(
+        &mut self,
+        start_block: u32,
+        end_block: u32,
+    ) -> Result<(), Error<SPI::Error>> {
+        self.check_awake()?;
+        if end_block <= start_block {
+            return Ok(());
+        }
+        let start_arg = self.block_arg(start_block);
+        let r1 = self.command(command::CMD32_ERASE_WR_BLK_START, start_arg).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+
+        let end_arg = self.block_arg(end_block.saturating_sub(1));
+        let r1 = self.command(command::CMD33_ERASE_WR_BLK_END, end_arg).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+
+        let r1 = self.command(command::CMD38_ERASE, 0).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+        // Erasing can take far longer than a single block write, so give it the same busy-wait
+        // budget used elsewhere rather than a tighter one.
+        self.wait_not_busy().await
+    }
+
+    async fn read_blocks_raw(
+        &mut self,
+        start_block: u32,
+        buf: &mut [u8],
+    ) -> Result<(), Error<SPI::Error>> {
+        self.check_awake()?;
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        match buf.len() / BLOCK_SIZE {
+            0 => Ok(()),
+            1 => self.read_single_block(start_block, buf).await,
+            _ => {
+                let arg = self.block_arg(start_block);
+                let r1 = self.command(command::CMD18_READ_MULTIPLE_BLOCK, arg).await?;
+                if r1 != 0x00 {
+                    return Err(Error::BadResponse(r1));
+                }
+                for chunk in buf.chunks_mut(BLOCK_SIZE) {
+                    self.read_data(chunk).await?;
+                }
+                self.command(command::CMD12_STOP_TRANSMISSION, 0).await?;
+                self.wait_not_busy().await
+            }
+        }
+    }
+
+    async fn write_blocks_raw(
+        &mut self,
+        start_block: u32,
+        buf: &[u8],
+    ) -> Result<(), Error<SPI::Error>> {
+        self.check_awake()?;
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        match buf.len() / BLOCK_SIZE {
+            0 => Ok(()),
+            1 => self.write_single_block(start_block, buf).await,
+            _ => {
+                let arg = self.block_arg(start_block);
+                let r1 = self.command(command::CMD25_WRITE_MULTIPLE_BLOCK, arg).await?;
+                if r1 != 0x00 {
+                    return Err(Error::BadResponse(r1));
+                }
+                for chunk in buf.chunks(BLOCK_SIZE) {
+                    self.write_data(command::WRITE_MULTIPLE_TOKEN, chunk).await?;
+                }
+                self.write_byte(command::STOP_TRAN_TOKEN).await?;
+                self.wait_not_busy().await
+            }
+        }
+    }
+
+    async fn read_single_block(
+        &mut self,
+        block_address: u32,
+        buf: &mut [u8],
+    ) -> Result<(), Error<SPI::Error>> {
+        let arg = self.block_arg(block_address);
+        let r1 = self.command(command::CMD17_READ_SINGLE_BLOCK, arg).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+        self.read_data(buf).await
+    }
+
+    async fn write_single_block(
+        &mut self,
+        block_address: u32,
+        buf: &[u8],
+    ) -> Result<(), Error<SPI::Error>> {
+        let arg = self.block_arg(block_address);
+        let r1 = self.command(command::CMD24_WRITE_BLOCK, arg).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+        self.write_data(command::DATA_START_BLOCK, buf).await
+    }
+
+    #[inline]
+    fn block_arg(&self, block_address: u32) -> u32 {
+        match self.card_type {
+            Some(CardType::BlockAddressed) => block_address,
+            _ => block_address * BLOCK_SIZE as u32,
+        }
+    }
+
+    async fn command(&mut self, cmd: u8, arg: u32) -> Result<u8, Error<SPI::Error>> {
+        let frame = command::frame(cmd, arg);
+        self.spi.write(&frame).await?;
+        // R1 is preceded by up to 8 filler bytes while the card prepares its response.
+        for _ in 0..8 {
+            let r1 = self.read_byte().await?;
+            if r1 & 0x80 == 0 {
+                return Ok(r1);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    async fn acmd(&mut self, cmd: u8, arg: u32) -> Result<u8, Error<SPI::Error>> {
+        self.command(command::CMD55_APP_CMD, 0).await?;
+        self.command(cmd, arg).await
+    }
+
+    async fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Error<SPI::Error>> {
+        let mut buf = [0u8; N];
+        for b in buf.iter_mut() {
+            *b = self.read_byte().await?;
+        }
+        Ok(buf)
+    }
+
+    async fn read_byte(&mut self) -> Result<u8, Error<SPI::Error>> {
+        let mut buf = [0xFFu8];
+        self.spi.transfer_in_place(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn write_byte(&mut self, byte: u8) -> Result<(), Error<SPI::Error>> {
+        self.spi.write(&[byte]).await?;
+        Ok(())
+    }
+
+    /// Wait for the data start token, then read the data block and check (or discard) the
+    /// trailing CRC16.
+    async fn read_data(&mut self, buf: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        let token = self.wait_for_token().await?;
+        if token != command::DATA_START_BLOCK {
+            return Err(Error::BadResponse(token));
+        }
+        self.spi.read(buf).await?;
+        let trailer = self.read_bytes::<2>().await?;
+
+        #[cfg(feature = "crc")]
+        {
+            let expected = crc::crc16(buf);
+            if trailer != expected.to_be_bytes() {
+                return Err(Error::CrcMismatch);
+            }
+        }
+        #[cfg(not(feature = "crc"))]
+        {
+            let _ = trailer; // CRC not checked; the card wasn't asked to compute one either.
+        }
+
+        Ok(())
+    }
+
+    /// Send `token` followed by `buf` and its CRC (real if the `crc` feature is on, dummy
+    /// otherwise), then confirm the card accepted the block and wait for it to stop holding the
+    /// line busy.
+    async fn write_data(&mut self, token: u8, buf: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.write_byte(token).await?;
+        self.spi.write(buf).await?;
+
+        #[cfg(feature = "crc")]
+        self.spi.write(&crc::crc16(buf).to_be_bytes()).await?;
+        #[cfg(not(feature = "crc"))]
+        self.spi.write(&[0xFF, 0xFF]).await?;
+
+        let resp = self.read_byte().await?;
+        if resp & 0x1F != 0x05 {
+            return Err(Error::WriteRejected(resp));
+        }
+        self.wait_not_busy().await
+    }
+
+    async fn wait_for_token(&mut self) -> Result<u8, Error<SPI::Error>> {
+        for _ in 0..8192 {
+            let b = self.read_byte().await?;
+            if b != 0xFF {
+                return Ok(b);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    async fn wait_not_busy(&mut self) -> Result<(), Error<SPI::Error>> {
+        for _ in 0..200_000 {
+            if self.read_byte().await? == 0xFF {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+}
+
+impl<SPI: SpiDevice, DELAY: DelayNs, ALIGN: aligned::Alignment, POWER> BlockDevice<BLOCK_SIZE>
+    for SdSpi<SPI, DELAY, ALIGN, POWER>
+{
+    type Error = Error<SPI::Error>;
+    type Align = ALIGN;
+
+    async fn read(
+        &mut self,
+        block_address: u32,
+        data: &mut [Aligned<Self::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<(), Self::Error> {
+        let buf = block_device_driver::blocks_to_slice_mut(data);
+        self.read_blocks_raw(block_address, buf).await
+    }
+
+    async fn write(
+        &mut self,
+        block_address: u32,
+        data: &[Aligned<Self::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<(), Self::Error> {
+        let buf = block_device_driver::blocks_to_slice(data);
+        self.write_blocks_raw(block_address, buf).await
+    }
+
+    async fn size(&mut self) -> Result<u64, Self::Error> {
+        self.check_awake()?;
+        let r1 = self.command(command::CMD9_SEND_CSD, 0).await?;
+        if r1 != 0x00 {
+            return Err(Error::BadResponse(r1));
+        }
+        let mut csd = [0u8; 16];
+        self.read_data(&mut csd).await?;
+        Ok(csd_size_bytes(&csd))
+    }
+}
+
+impl<SPI: SpiDevice, DELAY: DelayNs, ALIGN: aligned::Alignment, POWER> Discard<BLOCK_SIZE>
+    for SdSpi<SPI, DELAY, ALIGN, POWER>
+{
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        self.erase_blocks(block_address, block_address + count).await
+    }
+}
+
+/// Parse the card capacity out of a raw CSD register, handling both CSD structure versions.
+fn csd_size_bytes(csd: &[u8; 16]) -> u64 {
+    if csd[0] >> 6 == 1 {
+        // CSD version 2.0 (SDHC/SDXC): capacity = (C_SIZE + 1) * 512 KiB.
+        let c_size =
+            (((csd[7] & 0x3F) as u64) << 16) | ((csd[8] as u64) << 8) | csd[9] as u64;
+        (c_size + 1) * 512 * 1024
+    } else {
+        // CSD version 1.0 (SDSC): capacity = (C_SIZE + 1) * 2^(C_SIZE_MULT + 2) * 2^READ_BL_LEN.
+        let c_size = (((csd[6] & 0x03) as u64) << 10) | ((csd[7] as u64) << 2) | ((csd[8] >> 6) as u64);
+        let c_size_mult = (((csd[9] & 0x03) as u64) << 1) | (csd[10] >> 7) as u64;
+        let read_bl_len = (csd[5] & 0x0F) as u64;
+        (c_size + 1) * (1u64 << (c_size_mult + 2)) * (1u64 << read_bl_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use embedded_hal_async::spi::{ErrorType, Operation};
+
+    use super::*;
+
+    struct NoopDelay;
+
+    impl DelayNs for NoopDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// A scripted [`SpiDevice`] that returns canned bytes and records everything written to it.
+    struct MockSpi {
+        to_read: VecDeque<u8>,
+        written: Vec<u8>,
+    }
+
+    impl MockSpi {
+        fn new(to_read: impl IntoIterator<Item = u8>) -> Self {
+            Self {
+                to_read: to_read.into_iter().collect(),
+                written: Vec::new(),
+            }
+        }
+    }
+
+    impl ErrorType for MockSpi {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice for MockSpi {
+        async fn transaction(
+            &mut self,
+            operations: &mut [Operation<'_, u8>],
+        ) -> Result<(), Self::Error> {
+            for op in operations {
+                match op {
+                    Operation::Read(buf) => {
+                        for b in buf.iter_mut() {
+                            *b = self.to_read.pop_front().unwrap_or(0xFF);
+                        }
+                    }
+                    Operation::Write(buf) => self.written.extend_from_slice(buf),
+                    Operation::Transfer(read, write) => {
+                        self.written.extend_from_slice(write);
+                        for b in read.iter_mut() {
+                            *b = self.to_read.pop_front().unwrap_or(0xFF);
+                        }
+                    }
+                    Operation::TransferInPlace(buf) => {
+                        self.written.extend_from_slice(buf);
+                        for b in buf.iter_mut() {
+                            *b = self.to_read.pop_front().unwrap_or(0xFF);
+                        }
+                    }
+                    Operation::DelayNs(_) => {}
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Bytes an SDHC card would send back for a full init handshake (CMD0, CMD8, ACMD41, CMD58),
+    /// including the extra CMD59 round trip `init()` performs when the `crc` feature is on.
+    fn sdhc_init_script() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(0x01); // CMD0 -> in idle state
+        #[cfg(feature = "crc")]
+        bytes.push(0x00); // CMD59 -> ok
+        bytes.push(0x01); // CMD8 -> idle, supports CMD8
+        bytes.extend([0x00, 0x00, 0x01, 0xAA]); // R7 trailing bytes (echoes check pattern)
+        bytes.push(0x01); // CMD55 -> idle
+        bytes.push(0x00); // ACMD41 -> ready
+        bytes.push(0x00); // CMD58 -> ok
+        bytes.extend([0xC0, 0xFF, 0x80, 0x00]); // OCR with CCS bit (0x40) set -> block addressed
+        bytes
+    }
+
+    /// The two CRC16 trailer bytes a data block's script entry should use: the real CRC16 when
+    /// the `crc` feature is on (since `read_data` now verifies it), a dummy value otherwise.
+    fn data_trailer(block: &[u8]) -> [u8; 2] {
+        #[cfg(feature = "crc")]
+        {
+            crc::crc16(block).to_be_bytes()
+        }
+        #[cfg(not(feature = "crc"))]
+        {
+            let _ = block;
+            [0x00, 0x00]
+        }
+    }
+
+    #[tokio::test]
+    async fn sd_init_clocks_at_least_74_cycles() {
+        struct CountingBus(usize);
+        impl embedded_hal::spi::ErrorType for CountingBus {
+            type Error = core::convert::Infallible;
+        }
+        impl embedded_hal_async::spi::SpiBus<u8> for CountingBus {
+            async fn read(&mut self, _: &mut [u8]) -> Result<(), Self::Error> {
+                Ok(())
+            }
+            async fn write(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+                self.0 += buf.len();
+                Ok(())
+            }
+            async fn transfer(&mut self, _: &mut [u8], w: &[u8]) -> Result<(), Self::Error> {
+                self.0 += w.len();
+                Ok(())
+            }
+            async fn transfer_in_place(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+                self.0 += buf.len();
+                Ok(())
+            }
+            async fn flush(&mut self) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        let mut bus = CountingBus(0);
+        sd_init(&mut bus).await.unwrap();
+        assert!(bus.0 * 8 >= 74);
+    }
+
+    #[tokio::test]
+    async fn init_detects_sdhc_card() {
+        let spi = MockSpi::new(sdhc_init_script());
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+        assert_eq!(sd.card_type, Some(CardType::BlockAddressed));
+    }
+
+    #[tokio::test]
+    async fn read_blocks_single_uses_cmd17() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD17 -> ok
+        script.push(command::DATA_START_BLOCK);
+        script.extend([0xAA; BLOCK_SIZE]);
+        script.extend(data_trailer(&[0xAA; BLOCK_SIZE]));
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        let mut blocks = [[0u8; BLOCK_SIZE]];
+        sd.read_blocks(5, &mut blocks).await.unwrap();
+        assert_eq!(blocks[0], [0xAA; BLOCK_SIZE]);
+    }
+
+    #[cfg(feature = "crc")]
+    #[tokio::test]
+    async fn read_blocks_detects_crc_mismatch() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD17 -> ok
+        script.push(command::DATA_START_BLOCK);
+        script.extend([0xAA; BLOCK_SIZE]);
+        // Corrupt the real trailer so it no longer matches the block above.
+        let mut trailer = data_trailer(&[0xAA; BLOCK_SIZE]);
+        trailer[1] ^= 0xFF;
+        script.extend(trailer);
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        let mut blocks = [[0u8; BLOCK_SIZE]];
+        assert_eq!(
+            sd.read_blocks(5, &mut blocks).await,
+            Err(Error::CrcMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_blocks_multi_uses_cmd18_then_cmd12() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD18 -> ok
+        for fill in [0xAAu8, 0xBB] {
+            script.push(command::DATA_START_BLOCK);
+            script.extend([fill; BLOCK_SIZE]);
+            script.extend(data_trailer(&[fill; BLOCK_SIZE]));
+        }
+        script.push(0x00); // CMD12 -> ok
+        script.push(0xFF); // not busy
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        let mut blocks = [[0u8; BLOCK_SIZE]; 2];
+        sd.read_blocks(5, &mut blocks).await.unwrap();
+        assert_eq!(blocks[0], [0xAA; BLOCK_SIZE]);
+        assert_eq!(blocks[1], [0xBB; BLOCK_SIZE]);
+    }
+
+    #[tokio::test]
+    async fn write_blocks_multi_uses_cmd25_start_and_stop_tokens() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD25 -> ok
+        script.push(0x05); // data-response token: accepted
+        script.push(0xFF); // not busy
+        script.push(0x05); // data-response token: accepted
+        script.push(0xFF); // not busy
+        script.push(0xFF); // not busy after stop token
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        let blocks = [[0x11u8; BLOCK_SIZE], [0x22u8; BLOCK_SIZE]];
+        sd.write_blocks(5, &blocks).await.unwrap();
+
+        let spi = sd.into_inner();
+        // The write-multiple token and the final stop token must both have been sent.
+        assert!(spi.written.contains(&command::WRITE_MULTIPLE_TOKEN));
+        assert!(spi.written.contains(&command::STOP_TRAN_TOKEN));
+    }
+
+    #[tokio::test]
+    async fn write_single_block_returns_write_rejected_on_bad_token() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD24 -> ok
+        script.push(0x0D); // data-response token: rejected (write error)
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        let blocks = [[0x11u8; BLOCK_SIZE]];
+        assert_eq!(
+            sd.write_blocks(5, &blocks).await,
+            Err(Error::WriteRejected(0x0D))
+        );
+    }
+
+    #[tokio::test]
+    async fn erase_blocks_issues_cmd32_33_38() {
+        let mut script = sdhc_init_script();
+        script.push(0x00); // CMD32 -> ok
+        script.push(0x00); // CMD33 -> ok
+        script.push(0x00); // CMD38 -> ok
+        script.push(0xFF); // not busy
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        sd.erase_blocks(5, 7).await.unwrap();
+
+        let spi = sd.into_inner();
+        let cmd32 = 0x40 | command::CMD32_ERASE_WR_BLK_START;
+        let cmd33 = 0x40 | command::CMD33_ERASE_WR_BLK_END;
+        let cmd38 = 0x40 | command::CMD38_ERASE;
+        assert!(spi.written.contains(&cmd32));
+        assert!(spi.written.contains(&cmd33));
+        assert!(spi.written.contains(&cmd38));
+    }
+
+    #[tokio::test]
+    async fn erase_blocks_is_a_no_op_for_an_empty_range() {
+        let spi = MockSpi::new(sdhc_init_script());
+        let mut sd: SdSpi<_, _, aligned::A4> = SdSpi::new(spi, NoopDelay);
+        sd.init().await.unwrap();
+
+        sd.erase_blocks(5, 5).await.unwrap();
+        Discard::discard(&mut sd, 5, 0).await.unwrap();
+
+        let spi = sd.into_inner();
+        let cmd32 = 0x40 | command::CMD32_ERASE_WR_BLK_START;
+        assert!(!spi.written.contains(&cmd32));
+    }
+
+    #[test]
+    fn csd_size_bytes_parses_v1_csd() {
+        let mut csd = [0u8; 16];
+        csd[0] = 0x00; // CSD_STRUCTURE = 0 -> version 1.0 (SDSC)
+        csd[5] = 0x0A; // READ_BL_LEN = 10
+        csd[6] = 0x00;
+        csd[7] = 0x19;
+        csd[8] = 0x00;
+        csd[9] = 0x01;
+        csd[10] = 0x00;
+        // C_SIZE = 100, C_SIZE_MULT = 2, READ_BL_LEN = 10
+        // capacity = (100 + 1) * 2^(2 + 2) * 2^10
+        assert_eq!(csd_size_bytes(&csd), 101 * 16 * 1024);
+    }
+
+    #[test]
+    fn csd_size_bytes_parses_v2_csd() {
+        let mut csd = [0u8; 16];
+        csd[0] = 0x40; // CSD_STRUCTURE = 1 -> version 2.0 (SDHC/SDXC)
+        csd[7] = 0x00;
+        csd[8] = 0x00;
+        csd[9] = 0x01; // C_SIZE = 1
+        // capacity = (1 + 1) * 512 KiB
+        assert_eq!(csd_size_bytes(&csd), 2 * 512 * 1024);
+    }
+
+    /// A power-enable pin that records every level it's driven to.
+    struct MockPowerPin {
+        levels: Vec<bool>,
+    }
+
+    impl MockPowerPin {
+        fn new() -> Self {
+            Self { levels: Vec::new() }
+        }
+    }
+
+    impl embedded_hal::digital::ErrorType for MockPowerPin {
+        type Error = core::convert::Infallible;
+    }
+
+    impl OutputPin for MockPowerPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.levels.push(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.levels.push(true);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn sleep_cuts_power_and_blocks_transfers() {
+        let spi = MockSpi::new(sdhc_init_script());
+        let mut sd: SdSpi<_, _, aligned::A4, _> =
+            SdSpi::with_power_pin(spi, NoopDelay, MockPowerPin::new());
+        sd.init().await.unwrap();
+
+        sd.sleep().await.unwrap();
+
+        let mut blocks = [[0u8; BLOCK_SIZE]];
+        assert_eq!(sd.read_blocks(0, &mut blocks).await, Err(Error::Asleep));
+    }
+
+    #[tokio::test]
+    async fn wake_restores_power_and_reinitializes() {
+        let mut script = sdhc_init_script();
+        script.extend(sdhc_init_script());
+
+        let spi = MockSpi::new(script);
+        let mut sd: SdSpi<_, _, aligned::A4, _> =
+            SdSpi::with_power_pin(spi, NoopDelay, MockPowerPin::new());
+        sd.init().await.unwrap();
+
+        sd.sleep().await.unwrap();
+        sd.wake().await.unwrap();
+
+        assert_eq!(sd.card_type, Some(CardType::BlockAddressed));
+        assert_eq!(sd.power.levels, [false, true]);
+
+        // `wake` must not prime the card with dummy clocks through the CS-managing SpiDevice --
+        // that would assert CS low for the duration, which the SD SPI-mode spec forbids right
+        // after a power cycle. A raw 10-byte run of 0xFF (as opposed to the 0xFF filler bytes
+        // interleaved with command/response traffic) would only appear from such a priming write.
+        let spi = sd.into_inner();
+        assert!(!spi.written.windows(10).any(|w| w.iter().all(|&b| b == 0xFF)));
+    }
+}